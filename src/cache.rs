@@ -0,0 +1,81 @@
+//! Content-addressed caching for detection results.
+//!
+//! The cache key is the SHA-256 of the input image bytes plus the active
+//! detection parameters, so an identical re-upload (or two requests
+//! racing on the same upload) reuses one annotated output instead of
+//! re-running inference. The output itself lives at a path derived from
+//! that key, so the hash-to-output mapping doesn't need a separate index
+//! and survives restarts for free — a cache hit is just a `Store::exists`
+//! check that happens to succeed.
+
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::{config::DetectionConfig, store::Store};
+
+/// Compute the cache key for `input_bytes` under the given detection
+/// parameters. Changing a threshold invalidates prior cache entries,
+/// since it can change which boxes are drawn.
+pub fn cache_key(input_bytes: &[u8], model_input_size: u32, detection: &DetectionConfig) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(input_bytes);
+    hasher.update(model_input_size.to_le_bytes());
+    hasher.update(detection.confidence_threshold.to_le_bytes());
+    hasher.update(detection.nms_iou_threshold.to_le_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Coalesces concurrent requests for the same cache key so only one of
+/// them actually runs inference; the rest await its result.
+#[derive(Default)]
+pub struct Cache {
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<Result<String, String>>>>>,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached output path for `cache_path` if one already
+    /// exists, otherwise run `work` (at most once across concurrent
+    /// callers sharing the same `cache_path`) and return its result.
+    /// A failed `work` leaves the shared cell empty, so the next caller
+    /// still waiting on it runs its own `work`.
+    pub async fn get_or_run<F, Fut>(
+        &self,
+        store: &dyn Store,
+        cache_path: String,
+        work: F,
+    ) -> Result<String, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        if store.exists(&cache_path).await.unwrap_or(false) {
+            tracing::info!(cache_path = %cache_path, "cache hit");
+            return Ok(cache_path);
+        }
+
+        let cell = {
+            let mut in_flight = self.in_flight.lock().await;
+            in_flight
+                .entry(cache_path.clone())
+                .or_insert_with(|| Arc::new(OnceCell::new()))
+                .clone()
+        };
+
+        let result = cell
+            .get_or_try_init(|| async { work().await.map(|_| cache_path.clone()) })
+            .await
+            .cloned();
+
+        // Best-effort cleanup: whichever caller observes completion first
+        // removes the shared cell so future misses start a fresh one.
+        self.in_flight.lock().await.remove(&cache_path);
+
+        result
+    }
+}
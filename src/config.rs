@@ -0,0 +1,83 @@
+//! TOML-backed configuration for media limits, model selection, and
+//! detection parameters.
+//!
+//! Values are loaded from a TOML file (`CONFIG_PATH`, defaulting to
+//! `config.toml`) and can be overridden per-field with `APP__SECTION__KEY`
+//! environment variables (e.g. `APP__DETECTION__CONFIDENCE_THRESHOLD=0.6`),
+//! which mirrors how the rest of the service is tuned from the environment.
+
+use config::{Config as ConfigLoader, Environment, File};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Config {
+    pub media: MediaConfig,
+    pub model: ModelConfig,
+    pub detection: DetectionConfig,
+    pub server: ServerConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MediaConfig {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_file_size: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModelConfig {
+    pub path: String,
+    pub input_size: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DetectionConfig {
+    pub confidence_threshold: f32,
+    pub nms_iou_threshold: f32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerConfig {
+    pub address: String,
+}
+
+impl Config {
+    /// Load configuration from `CONFIG_PATH` (default `config.toml`),
+    /// falling back to the defaults below for anything the file omits,
+    /// then applying `APP__SECTION__KEY` environment overrides.
+    pub fn load() -> Result<Self, config::ConfigError> {
+        let path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".to_string());
+
+        ConfigLoader::builder()
+            .add_source(
+                ConfigLoader::try_from(&Config::default())?,
+            )
+            .add_source(File::with_name(&path).required(false))
+            .add_source(Environment::with_prefix("APP").separator("__"))
+            .build()?
+            .try_deserialize()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            media: MediaConfig {
+                max_width: 4096,
+                max_height: 4096,
+                max_file_size: 10 * 1024 * 1024,
+            },
+            model: ModelConfig {
+                path: "assets/yolov8m.onnx".to_string(),
+                input_size: 640,
+            },
+            detection: DetectionConfig {
+                confidence_threshold: 0.5,
+                nms_iou_threshold: 0.7,
+            },
+            server: ServerConfig {
+                address: "0.0.0.0:8080".to_string(),
+            },
+        }
+    }
+}
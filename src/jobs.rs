@@ -0,0 +1,37 @@
+//! Background job tracking for long-running processing (currently: video
+//! detection), so `/yolo/process` can hand back a pollable handle instead
+//! of blocking the request for the duration of the job.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed { output_path: String },
+    Failed { error: String },
+}
+
+/// Shared, clonable handle to the in-memory job table.
+#[derive(Clone, Default)]
+pub struct JobStore {
+    jobs: Arc<RwLock<HashMap<String, JobStatus>>>,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn set(&self, job_id: &str, status: JobStatus) {
+        self.jobs.write().await.insert(job_id.to_string(), status);
+    }
+
+    pub async fn get(&self, job_id: &str) -> Option<JobStatus> {
+        self.jobs.read().await.get(job_id).cloned()
+    }
+}
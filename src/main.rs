@@ -6,19 +6,41 @@ use axum::{
     routing::{get, post},
 };
 use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use image::GenericImageView;
 use minijinja::{Environment, context, path_loader};
 use serde::Serialize;
 use std::{ffi::OsStr, path::Path, sync::Arc};
 use tempfile::NamedTempFile;
+use tokio::sync::Semaphore;
 use tower_http::services::ServeDir;
+use tracing::Instrument;
 
+mod cache;
+mod config;
+mod jobs;
+mod store;
+mod video;
 mod yolov8m;
 
-use yolov8m::process_image;
+use cache::Cache;
+use config::Config;
+use jobs::JobStore;
+use ort::session::Session;
+use store::{FileStore, ObjectStore, ObjectStoreConfig, Store};
+use yolov8m::{job_status, process_image};
 
 #[derive(Clone)]
 struct AppState {
     env: Environment<'static>,
+    store: Arc<dyn Store>,
+    model: Arc<Session>,
+    /// Bounds how many inferences may run against `model` at once so that
+    /// concurrent uploads don't all try to grab the full GPU at the same
+    /// time; excess requests queue for a permit instead.
+    inference_permits: Arc<Semaphore>,
+    config: Arc<Config>,
+    jobs: JobStore,
+    cache: Arc<Cache>,
 }
 
 #[derive(TryFromMultipart)]
@@ -38,30 +60,79 @@ pub const PROCESS_DIR: &str = "static/";
 
 #[tokio::main]
 async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
+    let config = Arc::new(Config::load().expect("failed to load configuration"));
+
     let mut env = Environment::new();
     env.set_loader(path_loader("templates"));
 
-    // Create directories for storing files
-    //std::fs::create_dir_all("/tmp/processed").unwrap_or_default();
-    std::fs::create_dir_all(UPLOAD_DIR).unwrap_or_default();
+    let store = build_store().await;
+    let model = Arc::new(yolov8m::load_model(&config.model));
+    let concurrency: usize = std::env::var("MODEL_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
 
-    let app_state = Arc::new(AppState { env });
+    let app_state = Arc::new(AppState {
+        env,
+        store,
+        model,
+        inference_permits: Arc::new(Semaphore::new(concurrency)),
+        config: config.clone(),
+        jobs: JobStore::new(),
+        cache: Arc::new(Cache::new()),
+    });
 
     let app = Router::new()
         .route("/", get(root))
         .route("/pages/{page}", get(handle_page))
         .route("/yolo/upload", post(upload_image))
         .route("/yolo/process", post(process_image))
+        .route("/yolo/jobs/{job_id}", get(job_status))
+        .route("/yolo/results/{*key}", get(get_result))
         .nest_service("/static", ServeDir::new("static"))
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 10)) // 10MB is probably sufficient
+        .layer(DefaultBodyLimit::max(config.media.max_file_size as usize))
         .fallback(fallback)
         .with_state(app_state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    println!("listening on {}", listener.local_addr().unwrap());
+    let listener = tokio::net::TcpListener::bind(&config.server.address)
+        .await
+        .unwrap();
+    tracing::info!("listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Build the configured [`Store`] from environment variables.
+///
+/// `STORAGE_BACKEND=s3` selects [`ObjectStore`] (configured via
+/// `S3_BUCKET`/`S3_REGION`/`S3_ENDPOINT`/`S3_ACCESS_KEY`/`S3_SECRET_KEY`);
+/// anything else (including unset) falls back to the local-disk
+/// [`FileStore`] that backs `UPLOAD_DIR`/`PROCESS_DIR`.
+async fn build_store() -> Arc<dyn Store> {
+    match std::env::var("STORAGE_BACKEND").as_deref() {
+        Ok("s3") => {
+            let config = ObjectStoreConfig {
+                bucket: std::env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
+                region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                endpoint: std::env::var("S3_ENDPOINT").ok(),
+                access_key: std::env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set"),
+                secret_key: std::env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set"),
+            };
+            Arc::new(ObjectStore::new(config).await)
+        }
+        _ => {
+            std::fs::create_dir_all(UPLOAD_DIR).unwrap_or_default();
+            Arc::new(FileStore::new(".").expect("failed to initialize local file store"))
+        }
+    }
+}
+
 async fn root(State(state): State<Arc<AppState>>) -> Result<Html<String>, StatusCode> {
     let template = state.env.get_template("base.jinja").unwrap();
     let rendered = template.render(context!()).unwrap();
@@ -96,20 +167,104 @@ async fn fallback() -> (StatusCode, &'static str) {
     (StatusCode::NOT_FOUND, "Not Found")
 }
 
+/// Turn a store key (e.g. `static/cache/<hash>.png`) into the URL clients
+/// should fetch it from. `ServeDir` only ever serves `FileStore`'s local
+/// disk, so processed results must be read back through `/yolo/results/`
+/// (and therefore through the configured [`Store`]) to work against the
+/// S3 backend too.
+pub fn result_url(key: &str) -> String {
+    format!("/yolo/results/{key}")
+}
+
+/// Serve a processed image/video/detections sidecar through the
+/// configured [`Store`], so result retrieval works the same way against
+/// `FileStore` and `ObjectStore` instead of assuming local disk.
+async fn get_result(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(key): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.store.load(&key).await {
+        Ok(bytes) => {
+            let content_type = content_type_for(&key);
+            (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, content_type)],
+                bytes,
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, key = %key, "failed to load result");
+            (StatusCode::NOT_FOUND, "Result not found").into_response()
+        }
+    }
+}
+
+fn content_type_for(key: &str) -> &'static str {
+    match get_extension_from_filename(key).map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("mp4") => "video/mp4",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
 async fn upload_image(
+    State(state): State<Arc<AppState>>,
     TypedMultipart(UploadAssetRequest { image }): TypedMultipart<UploadAssetRequest>,
 ) -> impl IntoResponse {
     let id = uuid::Uuid::new_v4().to_string();
+    let span = tracing::info_span!("upload_image", image_id = %id);
+    upload_image_inner(state, image, id)
+        .instrument(span)
+        .await
+}
+
+async fn upload_image_inner(
+    state: Arc<AppState>,
+    image: FieldData<NamedTempFile>,
+    id: String,
+) -> impl IntoResponse {
     let file_name = image.metadata.file_name.unwrap();
     let image_extension = get_extension_from_filename(file_name.as_str()).unwrap();
     let file_path = format!("{}{}.{}", UPLOAD_DIR, id, image_extension);
 
-    // Ensure the file is an image
+    // Ensure the file is an image or a video clip
     match image.metadata.content_type {
         Some(content_type) if content_type.contains("image") => {
-            // Persist the file
-            match image.contents.persist(&file_path) {
-                Ok(_) => (
+            let media = &state.config.media;
+            match image::open(image.contents.path()) {
+                Ok(decoded)
+                    if decoded.width() > media.max_width || decoded.height() > media.max_height =>
+                {
+                    return (
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        Json(UploadResponse {
+                            image_id: String::new(),
+                            message: format!(
+                                "Image dimensions exceed the {}x{} limit",
+                                media.max_width, media.max_height
+                            ),
+                        }),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(error = ?e, "failed to decode uploaded image");
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(UploadResponse {
+                            image_id: String::new(),
+                            message: "Failed to decode uploaded image".to_string(),
+                        }),
+                    );
+                }
+            }
+
+            // Persist the file through the configured store
+            match state.store.save(&file_path, image.contents).await {
+                Ok(()) => (
                     StatusCode::OK,
                     Json(UploadResponse {
                         image_id: id,
@@ -117,7 +272,7 @@ async fn upload_image(
                     }),
                 ),
                 Err(e) => {
-                    eprintln!("Error saving file: {:?}", e);
+                    tracing::warn!(error = ?e, "error saving file");
                     (
                         StatusCode::INTERNAL_SERVER_ERROR,
                         Json(UploadResponse {
@@ -128,11 +283,36 @@ async fn upload_image(
                 }
             }
         }
+        Some(content_type) if content_type.contains("video") => {
+            // Persist the file through the configured store; frame
+            // dimensions are probed and checked against `media.max_width`/
+            // `max_height` once processing starts (decoding the clip here
+            // just to validate would mean probing it twice).
+            match state.store.save(&file_path, image.contents).await {
+                Ok(()) => (
+                    StatusCode::OK,
+                    Json(UploadResponse {
+                        image_id: id,
+                        message: "Video uploaded successfully".to_string(),
+                    }),
+                ),
+                Err(e) => {
+                    tracing::warn!(error = ?e, "error saving file");
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(UploadResponse {
+                            image_id: String::new(),
+                            message: "Failed to save video".to_string(),
+                        }),
+                    )
+                }
+            }
+        }
         _ => (
             StatusCode::IM_A_TEAPOT,
             Json(UploadResponse {
                 image_id: String::new(),
-                message: "The file must be an image".to_string(),
+                message: "The file must be an image or a video".to_string(),
             }),
         ),
     }
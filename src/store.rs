@@ -0,0 +1,253 @@
+//! Pluggable storage backend for uploaded and processed images.
+//!
+//! The playground used to assume everything lives on local disk
+//! (`UPLOAD_DIR`/`PROCESS_DIR`). [`Store`] abstracts that away so the same
+//! handler code can run against a plain filesystem or an S3-compatible
+//! bucket, which is what lets the service run statelessly behind a load
+//! balancer.
+
+use async_trait::async_trait;
+use aws_sdk_s3 as s3;
+use tempfile::NamedTempFile;
+
+/// A named blob store for uploaded and processed images.
+///
+/// Keys are slash-separated paths (e.g. `"static/cache/<hash>.png"`, or
+/// `"/tmp/uploaded/<uuid>.png"` for uploads); implementations are
+/// responsible for mapping them onto whatever addressing scheme they use
+/// internally (a local path, an object key, ...).
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Persist `file` under `key`, replacing anything already stored there.
+    async fn save(&self, key: &str, file: NamedTempFile) -> Result<(), StoreError>;
+
+    /// Write `bytes` under `key`, replacing anything already stored there.
+    async fn save_bytes(&self, key: &str, bytes: Vec<u8>) -> Result<(), StoreError>;
+
+    /// Load the bytes stored under `key`.
+    async fn load(&self, key: &str) -> Result<Vec<u8>, StoreError>;
+
+    /// Check whether `key` is present, without reading its contents.
+    async fn exists(&self, key: &str) -> Result<bool, StoreError>;
+
+    /// Remove the object stored under `key`, if any.
+    async fn remove(&self, key: &str) -> Result<(), StoreError>;
+
+    /// List keys stored under `prefix`, returned with `prefix` stripped.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("object not found: {0}")]
+    NotFound(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("object store error: {0}")]
+    Backend(String),
+}
+
+/// `Store` backed by the local filesystem, rooted at `root`.
+///
+/// This is the original `/tmp/uploaded` + `static/` behavior, just
+/// expressed behind the trait.
+pub struct FileStore {
+    root: std::path::PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path_for(&self, key: &str) -> std::path::PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl Store for FileStore {
+    async fn save(&self, key: &str, file: NamedTempFile) -> Result<(), StoreError> {
+        let dest = self.path_for(key);
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::task::spawn_blocking(move || file.persist(dest).map(|_| ()))
+            .await
+            .expect("save task panicked")
+            .map_err(|e| StoreError::Io(e.error))
+    }
+
+    async fn save_bytes(&self, key: &str, bytes: Vec<u8>) -> Result<(), StoreError> {
+        let dest = self.path_for(key);
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(dest, bytes).await?;
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        let path = self.path_for(key);
+        tokio::fs::read(&path)
+            .await
+            .map_err(|_| StoreError::NotFound(key.to_string()))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StoreError> {
+        match tokio::fs::metadata(self.path_for(key)).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(StoreError::Io(e)),
+        }
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), StoreError> {
+        let path = self.path_for(key);
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(StoreError::Io(e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let dir = self.path_for(prefix);
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str() {
+                keys.push(name.to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// `Store` backed by an S3-compatible object store.
+pub struct ObjectStore {
+    client: s3::Client,
+    bucket: String,
+}
+
+/// Connection details for [`ObjectStore`].
+pub struct ObjectStoreConfig {
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl ObjectStore {
+    pub async fn new(config: ObjectStoreConfig) -> Self {
+        let credentials = s3::config::Credentials::new(
+            config.access_key,
+            config.secret_key,
+            None,
+            None,
+            "onnx-playground",
+        );
+
+        let mut builder = s3::config::Builder::new()
+            .region(s3::config::Region::new(config.region))
+            .credentials_provider(credentials)
+            .behavior_version_latest();
+
+        if let Some(endpoint) = config.endpoint {
+            builder = builder.endpoint_url(endpoint).force_path_style(true);
+        }
+
+        Self {
+            client: s3::Client::from_conf(builder.build()),
+            bucket: config.bucket,
+        }
+    }
+}
+
+#[async_trait]
+impl Store for ObjectStore {
+    async fn save(&self, key: &str, file: NamedTempFile) -> Result<(), StoreError> {
+        let path = file.path().to_path_buf();
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(StoreError::Io)?;
+        self.save_bytes(key, bytes).await
+    }
+
+    async fn save_bytes(&self, key: &str, bytes: Vec<u8>) -> Result<(), StoreError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(bytes.into())
+            .send()
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|_| StoreError::NotFound(key.to_string()))?;
+
+        let bytes = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(bytes.into_bytes().to_vec())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StoreError> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(e) => Err(StoreError::Backend(e.to_string())),
+        }
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), StoreError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .send()
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+
+        Ok(output
+            .contents()
+            .iter()
+            .filter_map(|obj| obj.key())
+            .map(|key| key.strip_prefix(prefix).unwrap_or(key).to_string())
+            .collect())
+    }
+}
@@ -0,0 +1,252 @@
+//! Frame-by-frame video detection.
+//!
+//! Decodes an uploaded clip to raw frames with the system `ffmpeg`/`ffprobe`
+//! binaries, runs the same [`crate::yolov8m::run_detection`] inference and
+//! NMS used for single images on each frame, draws the boxes, and
+//! re-encodes the annotated frames into an output MP4. This is expensive,
+//! so callers should drive it from a background task and track progress
+//! through [`crate::jobs::JobStore`] rather than awaiting it inline in a
+//! request handler.
+
+use ort::session::Session;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{Child, ChildStderr, Command},
+    sync::Semaphore,
+};
+
+use crate::{
+    config::{DetectionConfig, MediaConfig, ModelConfig},
+    store::Store,
+    yolov8m::{draw_detections, run_detection},
+};
+
+pub async fn process_video(
+    store: &dyn Store,
+    model: &Session,
+    inference_permits: &Semaphore,
+    model_config: &ModelConfig,
+    detection_config: &DetectionConfig,
+    media_config: &MediaConfig,
+    input_path: &str,
+    output_path: &str,
+) -> Result<(), String> {
+    let input_bytes = store
+        .load(input_path)
+        .await
+        .map_err(|e| format!("failed to load input video: {e}"))?;
+
+    let input_file = tempfile::Builder::new()
+        .suffix(".mp4")
+        .tempfile()
+        .map_err(|e| format!("failed to create temp file: {e}"))?;
+    tokio::fs::write(input_file.path(), &input_bytes)
+        .await
+        .map_err(|e| format!("failed to write temp input: {e}"))?;
+
+    let (width, height, frame_rate) = probe_video(input_file.path()).await?;
+    if width > media_config.max_width || height > media_config.max_height {
+        return Err(format!(
+            "video dimensions {width}x{height} exceed the {}x{} limit",
+            media_config.max_width, media_config.max_height
+        ));
+    }
+
+    let mut decode = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(input_file.path())
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "pipe:1"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to launch ffmpeg decode: {e}"))?;
+    let mut decoded_frames = decode.stdout.take().expect("decode stdout is piped");
+
+    let output_file = tempfile::Builder::new()
+        .suffix(".mp4")
+        .tempfile()
+        .map_err(|e| format!("failed to create temp file: {e}"))?;
+    let mut encode = Command::new("ffmpeg")
+        .args(["-v", "error", "-f", "rawvideo", "-pix_fmt", "rgba", "-s"])
+        .arg(format!("{width}x{height}"))
+        .arg("-r")
+        .arg(frame_rate.to_string())
+        .args(["-i", "pipe:0", "-pix_fmt", "yuv420p", "-y"])
+        .arg(output_file.path())
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to launch ffmpeg encode: {e}"))?;
+    let mut encoded_frames = encode.stdin.take().expect("encode stdin is piped");
+    let encode_stderr = encode.stderr.take().expect("encode stderr is piped");
+
+    let frame_size = (width * height * 3) as usize;
+    let mut frame_buf = vec![0u8; frame_size];
+    let mut frame_index = 0usize;
+
+    loop {
+        match decoded_frames.read_exact(&mut frame_buf).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => {
+                return Err(abort_ffmpeg(
+                    decode,
+                    encode,
+                    encode_stderr,
+                    format!("failed reading decoded frame: {e}"),
+                )
+                .await);
+            }
+        }
+
+        let frame = match image::RgbImage::from_raw(width, height, frame_buf.clone()) {
+            Some(frame) => image::DynamicImage::ImageRgb8(frame),
+            None => {
+                return Err(abort_ffmpeg(
+                    decode,
+                    encode,
+                    encode_stderr,
+                    "decoded frame had an unexpected size".to_string(),
+                )
+                .await);
+            }
+        };
+
+        let detections = match run_detection(
+            model,
+            inference_permits,
+            model_config,
+            detection_config,
+            &frame,
+        )
+        .await
+        {
+            Ok(detections) => detections,
+            Err(e) => return Err(abort_ffmpeg(decode, encode, encode_stderr, e).await),
+        };
+        let annotated = draw_detections(&frame, &detections);
+
+        if let Err(e) = encoded_frames.write_all(&annotated).await {
+            return Err(abort_ffmpeg(
+                decode,
+                encode,
+                encode_stderr,
+                format!("failed writing annotated frame: {e}"),
+            )
+            .await);
+        }
+
+        tracing::info!(frame = frame_index, detections = detections.len(), "frame processed");
+        frame_index += 1;
+    }
+
+    drop(encoded_frames);
+    let decode_status = decode
+        .wait()
+        .await
+        .map_err(|e| format!("failed waiting on ffmpeg decode: {e}"))?;
+    let encode_status = encode
+        .wait()
+        .await
+        .map_err(|e| format!("failed waiting on ffmpeg encode: {e}"))?;
+    if !decode_status.success() || !encode_status.success() {
+        return Err(with_encode_stderr(
+            "ffmpeg exited with a non-zero status".to_string(),
+            encode_stderr,
+        )
+        .await);
+    }
+
+    let encoded_bytes = tokio::fs::read(output_file.path())
+        .await
+        .map_err(|e| format!("failed to read encoded output: {e}"))?;
+    store
+        .save_bytes(output_path, encoded_bytes)
+        .await
+        .map_err(|e| format!("failed to save annotated video: {e}"))?;
+
+    tracing::info!(frames = frame_index, "video processing done");
+
+    Ok(())
+}
+
+/// Kill and reap both ffmpeg children after a mid-loop failure, so a frame
+/// error doesn't orphan either `ffmpeg` process, then fold the encoder's
+/// stderr into `reason` for diagnostics.
+async fn abort_ffmpeg(
+    mut decode: Child,
+    mut encode: Child,
+    encode_stderr: ChildStderr,
+    reason: String,
+) -> String {
+    let _ = decode.start_kill();
+    let _ = encode.start_kill();
+    let _ = decode.wait().await;
+    let _ = encode.wait().await;
+    with_encode_stderr(reason, encode_stderr).await
+}
+
+async fn with_encode_stderr(reason: String, mut encode_stderr: ChildStderr) -> String {
+    let mut buf = Vec::new();
+    let _ = encode_stderr.read_to_end(&mut buf).await;
+    let stderr = String::from_utf8_lossy(&buf);
+    if stderr.trim().is_empty() {
+        reason
+    } else {
+        format!("{reason} (ffmpeg encode stderr: {})", stderr.trim())
+    }
+}
+
+async fn probe_video(path: &std::path::Path) -> Result<(u32, u32, f64), String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,r_frame_rate",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run ffprobe: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe exited with a non-zero status: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut parts = stdout.trim().split(',');
+    let width: u32 = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or("could not parse video width")?;
+    let height: u32 = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or("could not parse video height")?;
+    let frame_rate = parse_frame_rate(parts.next().ok_or("missing frame rate")?)?;
+
+    Ok((width, height, frame_rate))
+}
+
+fn parse_frame_rate(raw: &str) -> Result<f64, String> {
+    if let Some((num, den)) = raw.split_once('/') {
+        let num: f64 = num
+            .parse()
+            .map_err(|_| "invalid frame rate numerator".to_string())?;
+        let den: f64 = den
+            .parse()
+            .map_err(|_| "invalid frame rate denominator".to_string())?;
+        Ok(num / den)
+    } else {
+        raw.parse().map_err(|_| "invalid frame rate".to_string())
+    }
+}
+
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mov", "mkv"];
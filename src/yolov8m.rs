@@ -1,8 +1,4 @@
-use std::{
-    ffi::OsString,
-    path::{Path, PathBuf},
-    sync::Arc,
-};
+use std::sync::Arc;
 
 use ab_glyph::{FontRef, PxScale};
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
@@ -19,54 +15,122 @@ use ort::{
     value::Tensor,
 };
 use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tracing::{Instrument, info, info_span, warn};
+
+use crate::{
+    AppState, PROCESS_DIR, UPLOAD_DIR,
+    cache::cache_key,
+    config::{DetectionConfig, ModelConfig},
+    get_extension_from_filename,
+    jobs::JobStatus,
+    store::Store,
+};
 
-use crate::{AppState, PROCESS_DIR, UPLOAD_DIR, get_extension_from_filename};
+/// Build the shared ONNX session once at startup.
+///
+/// Re-creating this per request dominated latency and re-registered CUDA
+/// on every call; callers should hold on to the returned [`Session`]
+/// (e.g. behind an `Arc` in `AppState`) for the lifetime of the process.
+pub fn load_model(config: &ModelConfig) -> Session {
+    ort::init()
+        .with_execution_providers([CUDAExecutionProvider::default().build()])
+        .commit()
+        .unwrap();
+
+    Session::builder()
+        .unwrap()
+        .commit_from_file(&config.path)
+        .unwrap()
+}
+
+/// Whether `/yolo/process` should return the annotated image (the
+/// default) or the raw detections as JSON.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    #[default]
+    Image,
+    Json,
+}
 
 #[derive(Deserialize)]
 pub struct ProcessImageRequest {
     image_id: String,
+    #[serde(default)]
+    format: ResponseFormat,
 }
 
 #[derive(Serialize)]
 pub struct ProcessImageResponse {
     status: String,
     image_path: Option<String>,
+    /// Present when processing was handed off to a background job (video
+    /// clips); poll `/yolo/jobs/{job_id}` for the result.
+    job_id: Option<String>,
+    /// Present when the request asked for `format: "json"`.
+    detections: Option<Vec<DetectionDto>>,
+}
+
+#[derive(Serialize)]
+pub struct JobStatusResponse {
+    #[serde(flatten)]
+    status: JobStatus,
+}
+
+pub async fn job_status(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(job_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.jobs.get(&job_id).await {
+        Some(status) => (StatusCode::OK, Json(Some(JobStatusResponse { status }))),
+        None => (StatusCode::NOT_FOUND, Json(None)),
+    }
 }
 
 pub async fn process_image(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<ProcessImageRequest>,
+) -> impl IntoResponse {
+    let image_id = payload.image_id.clone();
+    let span = info_span!("process_image", image_id = %image_id);
+    process_image_inner(state, payload).instrument(span).await
+}
+
+async fn process_image_inner(
+    state: Arc<AppState>,
+    payload: ProcessImageRequest,
 ) -> impl IntoResponse {
     let image_id = payload.image_id;
+    let started_at = std::time::Instant::now();
 
     let mut input_path = String::new();
     let mut file_ext = "";
-    let mut file_name = OsString::new();
-    let mut file_path = PathBuf::new();
-
-    for path in std::fs::read_dir(UPLOAD_DIR).unwrap() {
-        let dir_entry = path.unwrap();
-        file_name = dir_entry.file_name();
-        let file_name_str = file_name.to_str().unwrap();
-        file_path = dir_entry.path();
-        let path_str = file_path.to_str().unwrap();
-        println!("File name: {}", file_name_str);
-        println!("Image ID: {}", image_id.as_str());
-        println!("File path: {}", path_str);
-        file_ext = match get_extension_from_filename(path_str) {
+
+    let entries = match state.store.list(UPLOAD_DIR).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(error = %e, "failed to list uploaded images");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ProcessImageResponse {
+                    status: "Failed to list uploaded images".to_string(),
+                    image_path: None,
+                    job_id: None,
+                    detections: None,
+                }),
+            );
+        }
+    };
+
+    for file_name_str in entries {
+        file_ext = match get_extension_from_filename(&file_name_str) {
             Some(ext) => ext,
-            None => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ProcessImageResponse {
-                        status: "Failed to get file extension".to_string(),
-                        image_path: None,
-                    }),
-                );
-            }
+            None => continue,
         };
-        if file_name_str == format!("{}.{}", image_id, file_ext).as_str() {
-            input_path = dir_entry.path().to_str().unwrap().to_string();
+        if file_name_str == format!("{}.{}", image_id, file_ext) {
+            input_path = format!("{}{}", UPLOAD_DIR, file_name_str);
+            break;
         }
     }
 
@@ -76,72 +140,322 @@ pub async fn process_image(
             Json(ProcessImageResponse {
                 status: "Image not found".to_string(),
                 image_path: None,
+                job_id: None,
+                detections: None,
             }),
         );
     }
 
     let output_path = format!("{}{}.{}", PROCESS_DIR, image_id, file_ext);
 
-    println!(
-        "Uploaded image: {}, output path: {}",
-        input_path, output_path
-    );
+    info!(input_path = %input_path, output_path = %output_path, "resolved uploaded image");
+
+    if crate::video::VIDEO_EXTENSIONS.contains(&file_ext) {
+        let job_id = uuid::Uuid::new_v4().to_string();
+        state.jobs.set(&job_id, JobStatus::Queued).await;
+
+        let state = state.clone();
+        let input_path = input_path.clone();
+        let output_path = output_path.clone();
+        let job_id_for_task = job_id.clone();
+        tokio::spawn(
+            async move {
+                state.jobs.set(&job_id_for_task, JobStatus::Running).await;
+                let result = crate::video::process_video(
+                    state.store.as_ref(),
+                    &state.model,
+                    &state.inference_permits,
+                    &state.config.model,
+                    &state.config.detection,
+                    &state.config.media,
+                    &input_path,
+                    &output_path,
+                )
+                .await;
+
+                let status = match result {
+                    Ok(()) => JobStatus::Completed {
+                        output_path: crate::result_url(&output_path),
+                    },
+                    Err(error) => {
+                        warn!(error = %error, "video job failed");
+                        JobStatus::Failed { error }
+                    }
+                };
+                state.jobs.set(&job_id_for_task, status).await;
+            }
+            .in_current_span(),
+        );
 
-    match identify_objects(&input_path, &output_path).await {
-        Ok(_) => (
-            StatusCode::OK,
+        return (
+            StatusCode::ACCEPTED,
             Json(ProcessImageResponse {
-                status: "Image processed successfully".to_string(),
-                image_path: Some(output_path),
+                status: "Video processing queued".to_string(),
+                image_path: None,
+                job_id: Some(job_id),
+                detections: None,
             }),
-        ),
+        );
+    }
+
+    let input_bytes = match state.store.load(&input_path).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(error = %e, "failed to load input image");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ProcessImageResponse {
+                    status: "Failed to load uploaded image".to_string(),
+                    image_path: None,
+                    job_id: None,
+                    detections: None,
+                }),
+            );
+        }
+    };
+    let key = cache_key(
+        &input_bytes,
+        state.config.model.input_size,
+        &state.config.detection,
+    );
+    let cache_path = format!("{}cache/{}.{}", PROCESS_DIR, key, file_ext);
+    let detections_path = format!("{}cache/{}.detections.json", PROCESS_DIR, key);
+
+    let result = state
+        .cache
+        .get_or_run(state.store.as_ref(), cache_path.clone(), || async {
+            identify_objects(
+                state.store.as_ref(),
+                &state.model,
+                &state.inference_permits,
+                &state.config.model,
+                &state.config.detection,
+                &input_bytes,
+                &cache_path,
+                &detections_path,
+            )
+            .await
+            .map(|_| ())
+        })
+        .in_current_span()
+        .await;
+
+    info!(elapsed_ms = started_at.elapsed().as_millis() as u64, "request finished");
+
+    match result {
+        Ok(output_path) => {
+            let output_path = crate::result_url(&output_path);
+            let detections = match payload.format {
+                ResponseFormat::Json => match state.store.load(&detections_path).await {
+                    Ok(bytes) => match serde_json::from_slice(&bytes) {
+                        Ok(detections) => Some(detections),
+                        Err(e) => {
+                            warn!(error = %e, "failed to parse cached detections");
+                            return (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                Json(ProcessImageResponse {
+                                    status: "Cached detections are corrupt".to_string(),
+                                    image_path: Some(output_path),
+                                    job_id: None,
+                                    detections: None,
+                                }),
+                            );
+                        }
+                    },
+                    Err(e) => {
+                        // The annotated image is cached but its detections
+                        // sidecar is missing (e.g. a cache entry written
+                        // before JSON mode existed). Don't let that look
+                        // like a legitimate "no detections" result.
+                        warn!(error = %e, "detections sidecar missing for cached image");
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(ProcessImageResponse {
+                                status: "Detections not available for this cached image".to_string(),
+                                image_path: Some(output_path),
+                                job_id: None,
+                                detections: None,
+                            }),
+                        );
+                    }
+                },
+                ResponseFormat::Image => None,
+            };
+
+            (
+                StatusCode::OK,
+                Json(ProcessImageResponse {
+                    status: "Image processed successfully".to_string(),
+                    image_path: Some(output_path),
+                    job_id: None,
+                    detections,
+                }),
+            )
+        }
         Err(err) => {
-            eprintln!("Error processing image: {:?}", err);
+            warn!(error = %err, "error processing image");
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ProcessImageResponse {
                     status: format!("Error processing image: {}", err),
                     image_path: None,
+                    job_id: None,
+                    detections: None,
                 }),
             )
         }
     }
 }
 
-async fn identify_objects(input_path: &str, output_dir: &str) -> Result<(), String> {
-    ort::init()
-        .with_execution_providers([CUDAExecutionProvider::default().build()])
-        .commit()
-        .unwrap();
+/// A detection in API-facing form: class label and confidence, plus both
+/// pixel and image-normalized box coordinates (pixel values divided by the
+/// image width/height, not clamped, so a box past an edge falls outside 0..1).
+#[derive(Serialize, Deserialize)]
+pub struct DetectionDto {
+    label: String,
+    confidence: f32,
+    pixel: BoundingBox,
+    normalized: BoundingBox,
+}
 
-    let original_img = image::open(Path::new(input_path)).unwrap();
-    let (img_width, img_height) = (original_img.width(), original_img.height());
-    let img = original_img.resize_exact(640, 640, FilterType::CatmullRom);
-    let mut input = Array::zeros((1, 3, 640, 640));
-    for pixel in img.pixels() {
-        let x = pixel.0 as _;
-        let y = pixel.1 as _;
-        let [r, g, b, _] = pixel.2.0;
-        input[[0, 0, y, x]] = (r as f32) / 255.;
-        input[[0, 1, y, x]] = (g as f32) / 255.;
-        input[[0, 2, y, x]] = (b as f32) / 255.;
-    }
+/// Run detection + NMS on `input_bytes`, draw and persist the annotated
+/// image at `output_dir`, and persist a JSON sidecar of the raw
+/// detections alongside it (so JSON-mode responses don't need to re-run
+/// inference on a cache hit). Returns the detections.
+async fn identify_objects(
+    store: &dyn Store,
+    model: &Session,
+    inference_permits: &Semaphore,
+    model_config: &ModelConfig,
+    detection_config: &DetectionConfig,
+    input_bytes: &[u8],
+    output_dir: &str,
+    detections_path: &str,
+) -> Result<Vec<DetectionDto>, String> {
+    let original_img = image::load_from_memory(input_bytes).unwrap();
+    let (img_width, img_height) = (original_img.width() as f32, original_img.height() as f32);
 
-    let model = Session::builder()
-        .unwrap()
-        .commit_from_file(YOLOV8M_PATH)
-        .unwrap();
+    let detections = run_detection(
+        model,
+        inference_permits,
+        model_config,
+        detection_config,
+        &original_img,
+    )
+    .await?;
 
-    // Run YOLOv8 inference
-    let outputs: SessionOutputs = model
-        .run(inputs!["images" => Tensor::from_array(input).unwrap()].unwrap())
-        .unwrap();
+    let annotated = draw_detections(&original_img, &detections);
+
+    let format = image::ImageFormat::from_path(output_dir)
+        .map_err(|e| format!("failed to determine output image format: {e}"))?;
+
+    let mut encoded = Vec::new();
+    let cursor = &mut std::io::Cursor::new(&mut encoded);
+    // JPEG has no alpha channel; writing the RGBA buffer straight to it
+    // makes the encoder reject an otherwise-valid image.
+    let write_result = if format == image::ImageFormat::Jpeg {
+        image::DynamicImage::ImageRgba8(annotated).to_rgb8().write_to(cursor, format)
+    } else {
+        annotated.write_to(cursor, format)
+    };
+    write_result.map_err(|e| format!("failed to encode annotated image: {e}"))?;
+    store
+        .save_bytes(output_dir, encoded)
+        .await
+        .map_err(|e| format!("failed to save annotated image: {e}"))?;
+
+    let detections: Vec<DetectionDto> = detections
+        .into_iter()
+        .map(|(pixel, label, confidence)| DetectionDto {
+            label: label.to_string(),
+            confidence,
+            normalized: BoundingBox {
+                x1: pixel.x1 / img_width,
+                y1: pixel.y1 / img_height,
+                x2: pixel.x2 / img_width,
+                y2: pixel.y2 / img_height,
+            },
+            pixel,
+        })
+        .collect();
+
+    let sidecar = serde_json::to_vec(&detections).map_err(|e| format!("failed to encode detections: {e}"))?;
+    store
+        .save_bytes(detections_path, sidecar)
+        .await
+        .map_err(|e| format!("failed to save detections: {e}"))?;
+
+    info!(detections = detections.len(), "detection results");
+
+    Ok(detections)
+}
+
+/// A single detected object: its box, class label, and confidence.
+pub type Detection = (BoundingBox, &'static str, f32);
+
+/// Run YOLOv8 inference and NMS over a single image, returning the
+/// surviving detections in the image's original pixel coordinates.
+///
+/// Shared between the single-image path and the per-frame video path, so
+/// both get the same preprocessing, inference, and NMS behavior.
+pub async fn run_detection(
+    model: &Session,
+    inference_permits: &Semaphore,
+    model_config: &ModelConfig,
+    detection_config: &DetectionConfig,
+    original_img: &image::DynamicImage,
+) -> Result<Vec<Detection>, String> {
+    let input_size = model_config.input_size;
+    let (img_width, img_height, input) = async {
+        let started_at = std::time::Instant::now();
+        let (img_width, img_height) = (original_img.width(), original_img.height());
+        let img = original_img.resize_exact(input_size, input_size, FilterType::CatmullRom);
+        let mut input = Array::zeros((1, 3, input_size as usize, input_size as usize));
+        for pixel in img.pixels() {
+            let x = pixel.0 as _;
+            let y = pixel.1 as _;
+            let [r, g, b, _] = pixel.2.0;
+            input[[0, 0, y, x]] = (r as f32) / 255.;
+            input[[0, 1, y, x]] = (g as f32) / 255.;
+            input[[0, 2, y, x]] = (b as f32) / 255.;
+        }
+        info!(
+            elapsed_ms = started_at.elapsed().as_millis() as u64,
+            "preprocessing done"
+        );
+        (img_width, img_height, input)
+    }
+    .instrument(info_span!("preprocess"))
+    .await;
+
+    let outputs = async {
+        let started_at = std::time::Instant::now();
+        // Bound concurrent GPU work; excess requests queue for a permit
+        // instead of each launching their own inference.
+        let _permit = inference_permits
+            .acquire()
+            .await
+            .expect("inference semaphore closed");
+        let outputs: SessionOutputs = model
+            .run(inputs!["images" => Tensor::from_array(input).unwrap()].unwrap())
+            .unwrap();
+        info!(
+            elapsed_ms = started_at.elapsed().as_millis() as u64,
+            "inference done"
+        );
+        outputs
+    }
+    .instrument(info_span!("model_run"))
+    .await;
     let output = outputs["output0"]
         .try_extract_tensor::<f32>()
         .unwrap()
         .t()
         .into_owned();
 
+    let started_at = std::time::Instant::now();
+    let _span = info_span!("postprocess_nms").entered();
+
     let mut boxes = Vec::new();
     let output = output.slice(s![.., .., 0]);
     for row in output.axis_iter(Axis(0)) {
@@ -154,14 +468,14 @@ async fn identify_objects(input_path: &str, output_dir: &str) -> Result<(), Stri
             .map(|(index, value)| (index, *value))
             .reduce(|accum, row| if row.1 > accum.1 { row } else { accum })
             .unwrap();
-        if prob < 0.5 {
+        if prob < detection_config.confidence_threshold {
             continue;
         }
         let label = YOLOV8_CLASS_LABELS[class_id];
-        let xc = row[0] / 640. * (img_width as f32);
-        let yc = row[1] / 640. * (img_height as f32);
-        let w = row[2] / 640. * (img_width as f32);
-        let h = row[3] / 640. * (img_height as f32);
+        let xc = row[0] / input_size as f32 * (img_width as f32);
+        let yc = row[1] / input_size as f32 * (img_height as f32);
+        let w = row[2] / input_size as f32 * (img_width as f32);
+        let h = row[3] / input_size as f32 * (img_height as f32);
         boxes.push((
             BoundingBox {
                 x1: xc - w / 2.,
@@ -173,24 +487,40 @@ async fn identify_objects(input_path: &str, output_dir: &str) -> Result<(), Stri
             prob,
         ));
 
-        println!("{}: {:.2}%", label, prob * 100.);
+        info!(class = label, confidence = prob, "candidate detection");
     }
 
     boxes.sort_by(|box1, box2| box2.2.total_cmp(&box1.2));
     let mut result = Vec::new();
 
-    let mut gray = img.to_rgba8();
-    let white = Rgba([255u8, 255u8, 255u8, 255u8]);
-
     while !boxes.is_empty() {
         result.push(boxes[0]);
         boxes = boxes
             .iter()
-            .filter(|box1| intersection(&boxes[0].0, &box1.0) / union(&boxes[0].0, &box1.0) < 0.7)
+            .filter(|box1| {
+                intersection(&boxes[0].0, &box1.0) / union(&boxes[0].0, &box1.0)
+                    < detection_config.nms_iou_threshold
+            })
             .copied()
             .collect();
     }
 
+    info!(
+        elapsed_ms = started_at.elapsed().as_millis() as u64,
+        detections = result.len(),
+        "nms done"
+    );
+    Ok(result)
+}
+
+/// Draw detection boxes and labels onto a copy of `img`.
+pub fn draw_detections(
+    img: &image::DynamicImage,
+    detections: &[Detection],
+) -> image::RgbaImage {
+    let mut gray = img.to_rgba8();
+    let white = Rgba([255u8, 255u8, 255u8, 255u8]);
+
     let font = FontRef::try_from_slice(include_bytes!("../assets/Roboto.ttf")).unwrap();
     let height = 15.0;
     let scale = PxScale {
@@ -198,7 +528,7 @@ async fn identify_objects(input_path: &str, output_dir: &str) -> Result<(), Stri
         y: height,
     };
 
-    for (box1, label, prob) in result.clone() {
+    for (box1, label, prob) in detections.iter().copied() {
         draw_hollow_rect_mut(
             &mut gray,
             Rect::at(box1.x1.floor() as i32, box1.y1.floor() as i32).of_size(
@@ -218,15 +548,11 @@ async fn identify_objects(input_path: &str, output_dir: &str) -> Result<(), Stri
         );
     }
 
-    gray.save(output_dir).unwrap();
-
-    println!("{:?}", result);
-
-    Ok(())
+    gray
 }
 
-#[derive(Debug, Clone, Copy)]
-struct BoundingBox {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BoundingBox {
     x1: f32,
     y1: f32,
     x2: f32,
@@ -242,8 +568,6 @@ fn union(box1: &BoundingBox, box2: &BoundingBox) -> f32 {
         - intersection(box1, box2)
 }
 
-const YOLOV8M_PATH: &str = "assets/yolov8m.onnx";
-
 #[rustfmt::skip]
 const YOLOV8_CLASS_LABELS:[&str;80] = [
     "person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck", "boat",